@@ -1,31 +1,119 @@
+use regex_automata::meta::Regex;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
+use std::time::Duration;
 
 #[derive(Deserialize)]
 #[serde(default = "Config::default")]
 pub struct Config {
     /// This specifies which docker containers to track
     pub docker_images: Vec<String>,
+    /// Additional label criteria pushed down to the Docker daemon's
+    /// `filters={"label":[...]}` query. `None` matches on label presence, `Some`
+    /// on an exact value. Combined with `docker_images`: a container must match
+    /// both, except that an empty `docker_images` imposes no image constraint so
+    /// selection is by label alone.
+    pub docker_labels: HashMap<String, Option<String>>,
     pub docker_socket: String,
     pub listen_address: String,
+    /// Regex applied to every log line; matching lines produce a metric.
+    pub log_pattern: String,
+    /// Maps a metric label name to the capture group index that fills it.
+    pub captures: HashMap<String, usize>,
+    /// Which backend to read logs from.
+    pub log_source: LogSourceConfig,
+    /// How often the dispatcher re-lists containers as a reconciliation safety
+    /// net. Accepts human-readable durations (e.g. `"60s"`, `"5m"`).
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Duration,
+    /// How often stale metric entries are swept.
+    #[serde(with = "humantime_serde")]
+    pub cleanup_interval: Duration,
+    /// How long a metric entry is kept after it was last seen.
+    #[serde(with = "humantime_serde")]
+    pub entry_ttl: Duration,
+}
+
+/// Selects the active log ingestion backend. `docker` (the default) follows
+/// container logs over the Docker socket; `file` tails a local log file; `tcp`
+/// accepts line-delimited log lines on a socket.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogSourceConfig {
+    Docker,
+    File { path: String },
+    Tcp { address: String },
 }
 
 impl Config {
     pub fn default() -> Config {
         Config {
             docker_images: vec!["atdr.meo.ws/archiveteam/urls-grab".to_string()],
+            docker_labels: HashMap::new(),
             docker_socket: "/var/run/docker.sock".to_string(),
             listen_address: "0.0.0.0:8000".to_string(),
+            log_pattern: "[0-9]+=([0-9]+) https?://([^/]+)/".to_string(),
+            captures: HashMap::from([
+                ("status_code".to_string(), 1),
+                ("domain".to_string(), 2),
+            ]),
+            log_source: LogSourceConfig::Docker,
+            poll_interval: Duration::from_secs(60),
+            cleanup_interval: Duration::from_secs(10),
+            entry_ttl: Duration::from_secs(60),
         }
     }
 
     pub fn validate(&self) -> Result<(), String> {
-        if self.docker_images.is_empty() {
-            return Err("docker_images can't be empty".to_string());
+        // The container selector and socket only apply to the Docker backend;
+        // file/tcp deployments don't touch the Docker socket at all.
+        if matches!(self.log_source, LogSourceConfig::Docker) {
+            if self.docker_images.is_empty() && self.docker_labels.is_empty() {
+                return Err(
+                    "at least one of docker_images or docker_labels must be set".to_string(),
+                );
+            }
+
+            if self.docker_labels.keys().any(|k| k.is_empty()) {
+                return Err("docker_labels keys can't be empty".to_string());
+            }
+
+            if self.docker_socket.is_empty() {
+                return Err("docker_socket can't be empty".to_string());
+            }
+        }
+
+        match &self.log_source {
+            LogSourceConfig::Docker => {}
+            LogSourceConfig::File { path } => {
+                if path.is_empty() {
+                    return Err("log_source.path can't be empty".to_string());
+                }
+            }
+            LogSourceConfig::Tcp { address } => {
+                if address.is_empty() {
+                    return Err("log_source.address can't be empty".to_string());
+                }
+            }
         }
 
-        if self.docker_socket.is_empty() {
-            return Err("docker_socket can't be empty".to_string());
+        if self.captures.is_empty() {
+            return Err("captures can't be empty".to_string());
+        }
+
+        let re = Regex::new(&self.log_pattern)
+            .map_err(|e| format!("log_pattern failed to compile: {e}"))?;
+        // captures_len() counts the implicit whole-match group, so the highest
+        // addressable explicit group index is captures_len() - 1.
+        let groups = re.captures_len();
+        for (label, group) in &self.captures {
+            if *group >= groups {
+                return Err(format!(
+                    "capture group {group} for label '{label}' does not exist in log_pattern ({} capture group(s))",
+                    groups.saturating_sub(1)
+                ));
+            }
         }
 
         Ok(())
@@ -38,9 +126,66 @@ impl Config {
                     Ok(_) => Ok(c),
                     Err(e) => Err(format!("Config failed to validate: {e}")),
                 },
-                Err(e) => Err(format!("Failed to read config from 'config.json': {}", e)),
+                Err(e) => Err(format!("Failed to read config from '{path}': {}", e)),
             },
-            Err(e) => Err(format!("Failed to open 'config.json' for reading: {}", e)),
+            Err(e) => Err(format!("Failed to open '{path}' for reading: {}", e)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_validates() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_selectors() {
+        let mut config = Config::default();
+        config.docker_images.clear();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn labels_only_is_allowed() {
+        let mut config = Config::default();
+        config.docker_images.clear();
+        config
+            .docker_labels
+            .insert("role".to_string(), Some("grab".to_string()));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_label_key() {
+        let mut config = Config::default();
+        config.docker_labels.insert(String::new(), None);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_uncompilable_pattern() {
+        let mut config = Config::default();
+        config.log_pattern = "([0-9]+".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_bounds_capture_group() {
+        let mut config = Config::default();
+        // The pattern has one capture group (index 1), so group 5 is invalid.
+        config.log_pattern = "([0-9]+)".to_string();
+        config.captures = HashMap::from([("status_code".to_string(), 5)]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_empty_captures() {
+        let mut config = Config::default();
+        config.captures.clear();
+        assert!(config.validate().is_err());
+    }
+}
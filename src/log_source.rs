@@ -0,0 +1,189 @@
+use std::future::Future;
+use std::io::SeekFrom;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use log::info;
+use reqwest::{Client, StatusCode};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+/// A byte stream of raw log output. The watcher wraps it in a `StreamReader`
+/// and splits it into lines, exactly like the original Docker-specific path.
+pub type LogStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// A pluggable source of log output. Implementations open a fresh byte stream
+/// each time `stream` is called; the watcher re-calls it to reconnect after an
+/// error, so a source must be usable more than once.
+pub trait LogSource: Send + Sync + 'static {
+    fn stream(&self) -> Pin<Box<dyn Future<Output = Result<LogStream, String>> + Send + '_>>;
+}
+
+fn io_other<E>(e: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
+
+/// Follows a container's log output over the Docker socket
+/// (`/containers/{id}/logs?follow=true`) — the original behaviour.
+pub struct DockerLogSource {
+    client: Client,
+    container_id: String,
+}
+
+impl DockerLogSource {
+    pub fn new(client: Client, container_id: String) -> Self {
+        Self {
+            client,
+            container_id,
+        }
+    }
+}
+
+impl LogSource for DockerLogSource {
+    fn stream(&self) -> Pin<Box<dyn Future<Output = Result<LogStream, String>> + Send + '_>> {
+        let client = self.client.clone();
+        let uri = format!(
+            "http://docker/v1.30/containers/{}/logs?follow=true&stdout=true&since={}",
+            self.container_id,
+            chrono::Utc::now().timestamp()
+        );
+        Box::pin(async move {
+            let res = client
+                .get(&uri)
+                .send()
+                .await
+                .map_err(|e| format!("request failed: {e:#?}"))?;
+            if res.status() != StatusCode::OK {
+                return Err(format!("got non-200 status code: {}", res.status()));
+            }
+            let stream = res.bytes_stream().map(|res| res.map_err(io_other));
+            Ok(Box::pin(stream) as LogStream)
+        })
+    }
+}
+
+/// Tails a local log file, following rotation: when the file is replaced or
+/// truncated it is reopened from the start.
+pub struct FileLogSource {
+    path: PathBuf,
+}
+
+impl FileLogSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+struct Tail {
+    path: PathBuf,
+    file: File,
+    inode: u64,
+}
+
+impl Tail {
+    /// Reopen the file from the start if it has been rotated (inode changed) or
+    /// truncated below our current read position.
+    async fn maybe_reopen(&mut self) -> Result<(), std::io::Error> {
+        let meta = tokio::fs::metadata(&self.path).await?;
+        let pos = self.file.stream_position().await?;
+        if meta.ino() != self.inode || meta.len() < pos {
+            self.file = File::open(&self.path).await?;
+            self.inode = meta.ino();
+        }
+        Ok(())
+    }
+}
+
+impl LogSource for FileLogSource {
+    fn stream(&self) -> Pin<Box<dyn Future<Output = Result<LogStream, String>> + Send + '_>> {
+        let path = self.path.clone();
+        Box::pin(async move {
+            let mut file = File::open(&path)
+                .await
+                .map_err(|e| format!("failed to open log file '{}': {e}", path.display()))?;
+            // Start at the end so we only report newly written lines.
+            file.seek(SeekFrom::End(0))
+                .await
+                .map_err(|e| format!("failed to seek log file '{}': {e}", path.display()))?;
+            let meta = file
+                .metadata()
+                .await
+                .map_err(|e| format!("failed to stat log file '{}': {e}", path.display()))?;
+            let tail = Tail {
+                path,
+                file,
+                inode: meta.ino(),
+            };
+            let stream = futures_util::stream::unfold(tail, |mut tail| async move {
+                let mut buf = vec![0u8; 8192];
+                loop {
+                    match tail.file.read(&mut buf).await {
+                        Ok(0) => {
+                            // At EOF: wait for more data, handling rotation.
+                            tokio::time::sleep(Duration::from_millis(500)).await;
+                            if let Err(e) = tail.maybe_reopen().await {
+                                return Some((Err(e), tail));
+                            }
+                        }
+                        Ok(n) => {
+                            return Some((Ok(Bytes::copy_from_slice(&buf[..n])), tail));
+                        }
+                        Err(e) => return Some((Err(e), tail)),
+                    }
+                }
+            });
+            Ok(Box::pin(stream) as LogStream)
+        })
+    }
+}
+
+/// Accepts line-delimited log connections over TCP, one at a time. The port is
+/// bound once and re-`accept()`ed across reconnects, so producers always have a
+/// listening socket to connect to.
+pub struct TcpLogSource {
+    address: String,
+    listener: Mutex<Option<TcpListener>>,
+}
+
+impl TcpLogSource {
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            listener: Mutex::new(None),
+        }
+    }
+}
+
+impl LogSource for TcpLogSource {
+    fn stream(&self) -> Pin<Box<dyn Future<Output = Result<LogStream, String>> + Send + '_>> {
+        Box::pin(async move {
+            // Bind lazily on first use, then keep the listener for the lifetime
+            // of the source so reconnects just accept the next connection.
+            let mut guard = self.listener.lock().await;
+            if guard.is_none() {
+                *guard = Some(
+                    TcpListener::bind(&self.address)
+                        .await
+                        .map_err(|e| format!("failed to bind '{}': {e}", self.address))?,
+                );
+            }
+            let listener = guard.as_ref().expect("listener was just bound");
+            let (socket, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| format!("failed to accept on '{}': {e}", self.address))?;
+            info!("Accepted log connection from {peer}");
+            let stream = tokio_util::io::ReaderStream::new(socket);
+            Ok(Box::pin(stream) as LogStream)
+        })
+    }
+}
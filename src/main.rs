@@ -1,14 +1,16 @@
 use futures_util::StreamExt;
 mod config;
+mod log_source;
 mod metrics;
 
 use std::collections::{HashMap, HashSet};
 use std::process::exit;
-use std::str::FromStr;
 use std::sync::Arc;
 
-use crate::config::Config;
+use crate::config::{Config, LogSourceConfig};
+use crate::log_source::{DockerLogSource, FileLogSource, LogSource, TcpLogSource};
 use crate::metrics::Metrics;
+use clap::Parser;
 use fern::colors::ColoredLevelConfig;
 use log::{error, info, warn};
 use regex_automata::meta::Regex;
@@ -27,6 +29,28 @@ struct ContainerWatcher {
     task: JoinHandle<()>,
 }
 
+/// Command-line overrides. Anything left unset falls back to the value from the
+/// config file. Durations accept human-readable forms like `35s` or `5m`.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Path to the JSON config file.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+    /// Address the `/metrics` server listens on.
+    #[arg(long)]
+    listen_address: Option<String>,
+    /// How often stale metric entries are swept.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    cleanup_interval: Option<std::time::Duration>,
+    /// How long a metric entry is kept after it was last seen.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    entry_ttl: Option<std::time::Duration>,
+    /// How often the dispatcher re-lists containers as a safety net.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    poll_interval: Option<std::time::Duration>,
+}
+
 #[tokio::main]
 async fn main() {
     let colors = ColoredLevelConfig::new();
@@ -45,27 +69,58 @@ async fn main() {
         .apply()
         .ok();
 
-    let config = match config::Config::load_from_file("config.json") {
-        Ok(config) => Arc::new(config),
+    let cli = Cli::parse();
+
+    let mut config = match config::Config::load_from_file(&cli.config) {
+        Ok(config) => config,
         Err(e) => {
             error!("Failed to load config: {}", e);
             exit(1);
         }
     };
 
+    // CLI flags override the values from the config file.
+    if let Some(listen_address) = cli.listen_address {
+        config.listen_address = listen_address;
+    }
+    if let Some(cleanup_interval) = cli.cleanup_interval {
+        config.cleanup_interval = cleanup_interval;
+    }
+    if let Some(entry_ttl) = cli.entry_ttl {
+        config.entry_ttl = entry_ttl;
+    }
+    if let Some(poll_interval) = cli.poll_interval {
+        config.poll_interval = poll_interval;
+    }
+
+    let config = Arc::new(config);
+
     let cancel = CancellationToken::new();
 
     let metrics = Metrics::new(&config, cancel.clone());
-    let client = Client::builder()
-        .unix_socket(config.docker_socket.as_str())
-        .build()
-        .unwrap();
-    let dispatcher = tokio::spawn(dispatcher(
-        config.clone(),
-        client.clone(),
-        metrics,
-        cancel.clone(),
-    ));
+
+    // The Docker backend watches many containers via the dispatcher; the
+    // file/tcp backends are a single log stream, so they run one watcher
+    // directly over the configured source.
+    let task: JoinHandle<()> = match &config.log_source {
+        LogSourceConfig::Docker => {
+            let client = Client::builder()
+                .unix_socket(config.docker_socket.as_str())
+                .build()
+                .unwrap();
+            tokio::spawn(dispatcher(config.clone(), client, metrics, cancel.clone()))
+        }
+        LogSourceConfig::File { path } => {
+            let source = Box::new(FileLogSource::new(path.clone()));
+            let label = vec![("source".to_string(), path.clone())];
+            tokio::spawn(watcher(source, label, config.clone(), metrics, cancel.clone()))
+        }
+        LogSourceConfig::Tcp { address } => {
+            let source = Box::new(TcpLogSource::new(address.clone()));
+            let label = vec![("source".to_string(), address.clone())];
+            tokio::spawn(watcher(source, label, config.clone(), metrics, cancel.clone()))
+        }
+    };
 
     match signal::ctrl_c().await {
         Ok(()) => {
@@ -78,87 +133,71 @@ async fn main() {
         }
     }
 
-    if let Err(e) = dispatcher.await {
-        error!("Failed to join dispatcher: {}", e);
+    if let Err(e) = task.await {
+        error!("Failed to join main task: {}", e);
     }
 
     info!("Goodbye.")
 }
 
+/// A container lifecycle event received from the Docker events stream.
+enum ContainerEvent {
+    /// A container matching our filters started; carries its id and (tag-stripped) image.
+    Start { id: String, image: String },
+    /// A container matching our filters died or was stopped.
+    Stop { id: String },
+}
+
 async fn dispatcher(
     config: Arc<Config>,
     client: Client,
     metrics: Arc<Metrics>,
     cancel: CancellationToken,
 ) {
-    let na_name: String = "<none>".to_string();
     let mut containers = HashMap::<String, ContainerWatcher>::new();
+
+    // Event-driven fast path: a background task tails /events and feeds us
+    // container start/stop events so watchers come and go near-instantly.
+    let (tx, rx) = tokio::sync::mpsc::channel::<ContainerEvent>(64);
+    // `None` once the events task has exited; the reconciliation loop keeps
+    // the watcher set current on its own from then on.
+    let mut rx = Some(rx);
+    let events = tokio::spawn(watch_events(config.clone(), client.clone(), tx, cancel.clone()));
+
+    // Seed the initial set before we start reacting to events.
+    reconcile(&config, &client, &metrics, &mut containers).await;
+
+    // Low-frequency reconciliation as a safety net: recovers from dropped
+    // events and reaps anything the stream missed.
+    let mut reconcile_interval = tokio::time::interval(config.poll_interval);
+    reconcile_interval.tick().await; // consume the immediate first tick, we just seeded
+
     loop {
-        let mut sleep_duration = std::time::Duration::from_secs(60);
         select! {
             _ = cancel.cancelled() => {
                 break;
             }
-            c = list_containers(client.clone()) => {
-                match c {
-                    Ok(list_containers) => {
-                        let mut alive = HashSet::<String>::new();
-                        for container in list_containers {
-                            let image = container.image.rsplit_once(":").unwrap_or((&container.image, "")).0;
-
-                            let mut found = false;
-                            for docker_image in &config.docker_images {
-                                if docker_image == image {
-                                    found = true;
-                                    break;
-                                }
-                            }
-                            if !found {
-                                continue;
-                            }
-
-                            if !containers.contains_key(&container.id) {
-                                let watcher_cancel = CancellationToken::new();
-                                info!("Starting watcher for container {} (id {})", container.names.first().or(Some(&na_name)).unwrap(), container.id);
-                                containers.insert(container.id.clone(), ContainerWatcher {
-                                    task: tokio::spawn(watcher(container.id.clone(), client.clone(), metrics.clone(), watcher_cancel.clone())),
-                                    cancel: watcher_cancel,
-                                    name: container.names.first().or(Some(&container.id)).unwrap().to_string(),
-                                });
-                            }
-                            alive.insert(container.id);
-                        }
-                        let mut to_remove = vec![];
-                        // remove containers that are no longer alive
-                        for id in containers.keys() {
-                            if !alive.contains(id) {
-                                to_remove.push(id.clone())
-                            }
-                        }
-
-                        for id in to_remove {
-                            let container = containers.remove(&id).unwrap();
-                            info!("Stopping watcher for container {}/{}", container.name, id);
-                            container.cancel.cancel();
-                            if let Err(e) = container.task.await {
-                                warn!("Failed to join the watcher task for container {}/{}: {}", container.name, id, e);
-                            }
+            _ = reconcile_interval.tick() => {
+                reconcile(&config, &client, &metrics, &mut containers).await;
+            }
+            // select! builds every branch's future even when its precondition
+            // is false, so the unwrap is deferred into the (unpolled) future.
+            ev = async { rx.as_mut().unwrap().recv().await }, if rx.is_some() => {
+                match ev {
+                    Some(ContainerEvent::Start { id, image }) => {
+                        if image_matches(&config, &image) {
+                            spawn_watcher(&mut containers, id.clone(), id, &client, &config, &metrics);
                         }
-                    },
-                    Err(e) => {
-                        warn!("Failed to list containers: {e}");
-                        sleep_duration = std::time::Duration::from_secs(10);
-                    },
+                    }
+                    Some(ContainerEvent::Stop { id }) => {
+                        stop_watcher(&mut containers, &id, &metrics).await;
+                    }
+                    // The events task exited; drop the branch and let
+                    // reconciliation keep us going.
+                    None => rx = None,
                 }
             }
         }
-
-        select! {
-            _ = cancel.cancelled() => {
-                break;
-            }
-            _ = sleep(sleep_duration) => {}
-        }
     }
 
     for container in containers.values() {
@@ -173,20 +212,137 @@ async fn dispatcher(
             );
         }
     }
+
+    if let Err(e) = events.await {
+        warn!("Failed to join the events task: {e}");
+    }
 }
 
-async fn watcher(
-    container_id: String,
+/// Whether a (tag-stripped) image name is one we're configured to watch. An
+/// empty `docker_images` imposes no image constraint, so a labels-only config
+/// relies solely on the daemon-side label filter.
+fn image_matches(config: &Config, image: &str) -> bool {
+    config.docker_images.is_empty() || config.docker_images.iter().any(|i| i == image)
+}
+
+/// Spawn a watcher for `id` unless one is already running.
+fn spawn_watcher(
+    containers: &mut HashMap<String, ContainerWatcher>,
+    id: String,
+    name: String,
+    client: &Client,
+    config: &Arc<Config>,
+    metrics: &Arc<Metrics>,
+) {
+    if containers.contains_key(&id) {
+        return;
+    }
+    let watcher_cancel = CancellationToken::new();
+    info!("Starting watcher for container {} (id {})", name, id);
+    let source = Box::new(DockerLogSource::new(client.clone(), id.clone()));
+    let label = vec![("container".to_string(), id.clone())];
+    containers.insert(
+        id,
+        ContainerWatcher {
+            task: tokio::spawn(watcher(
+                source,
+                label,
+                config.clone(),
+                metrics.clone(),
+                watcher_cancel.clone(),
+            )),
+            cancel: watcher_cancel,
+            name,
+        },
+    );
+    metrics.watcher_started();
+}
+
+/// Cancel and join the watcher for `id`, if one is running.
+async fn stop_watcher(
+    containers: &mut HashMap<String, ContainerWatcher>,
+    id: &str,
+    metrics: &Arc<Metrics>,
+) {
+    if let Some(container) = containers.remove(id) {
+        info!("Stopping watcher for container {}/{}", container.name, id);
+        container.cancel.cancel();
+        if let Err(e) = container.task.await {
+            warn!(
+                "Failed to join the watcher task for container {}/{}: {}",
+                container.name, id, e
+            );
+        }
+        metrics.watcher_stopped();
+    }
+}
+
+/// Full reconciliation pass against `/containers/json`: start watchers for
+/// matching containers we're not yet tracking and stop watchers for ones that
+/// have gone away.
+async fn reconcile(
+    config: &Arc<Config>,
+    client: &Client,
+    metrics: &Arc<Metrics>,
+    containers: &mut HashMap<String, ContainerWatcher>,
+) {
+    let list = match list_containers(config, client.clone()).await {
+        Ok(list) => list,
+        Err(e) => {
+            warn!("Failed to list containers: {e}");
+            return;
+        }
+    };
+
+    let mut alive = HashSet::<String>::new();
+    for container in list {
+        let image = container
+            .image
+            .rsplit_once(":")
+            .unwrap_or((&container.image, ""))
+            .0;
+        if !image_matches(config, image) {
+            continue;
+        }
+        let name = container
+            .names
+            .first()
+            .unwrap_or(&container.id)
+            .to_string();
+        spawn_watcher(containers, container.id.clone(), name, client, config, metrics);
+        alive.insert(container.id);
+    }
+
+    // Remove containers that are no longer alive.
+    let to_remove: Vec<String> = containers
+        .keys()
+        .filter(|id| !alive.contains(*id))
+        .cloned()
+        .collect();
+    for id in to_remove {
+        stop_watcher(containers, &id, metrics).await;
+    }
+}
+
+/// Tail the Docker daemon's event stream, forwarding matching container
+/// lifecycle events to the dispatcher. Reconnects with exponential backoff if
+/// the stream errors out or closes.
+async fn watch_events(
+    config: Arc<Config>,
     client: Client,
-    metrics: Arc<Metrics>,
+    tx: tokio::sync::mpsc::Sender<ContainerEvent>,
     cancel: CancellationToken,
 ) {
-    let uri = format!(
-        "http://docker/v1.30/containers/{}/logs?follow=true&stdout=true&since={}",
-        container_id,
-        chrono::Utc::now().timestamp()
-    );
-    let re = Regex::new("[0-9]+=([0-9]+) https?://([^/]+)/").expect("invalid regex (how?)");
+    let mut filters = serde_json::json!({
+        "type": ["container"],
+        "event": ["start", "die", "stop"],
+    });
+    let labels = label_filters(&config);
+    if !labels.is_empty() {
+        filters["label"] = serde_json::json!(labels);
+    }
+    let filters = filters.to_string();
+    let mut backoff = std::time::Duration::from_secs(1);
     loop {
         select! {
             _ = cancel.cancelled() => {
@@ -194,20 +350,95 @@ async fn watcher(
             }
             r = async {
                     let res = client
-                        .get(&uri)
+                        .get("http://docker/v1.30/events")
+                        .query(&[("filters", filters.as_str())])
                         .send()
                         .await
                         .map_err(|e| format!("request failed: {e:#?}"))?;
                     if res.status() != StatusCode::OK {
                         return Err(format!("got non-200 status code: {}", res.status()));
                     }
-                    // Ensure success
-                    let stream = res.bytes_stream();
+                    // Connected successfully, reset the backoff.
+                    backoff = std::time::Duration::from_secs(1);
 
-                    // Convert to async reader
+                    let stream = res.bytes_stream();
                     let stream_reader = tokio_util::io::StreamReader::new(
                         stream.map(|res| res.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))),
                     );
+                    let mut lines = BufReader::new(stream_reader);
+                    let mut buf = Vec::new();
+                    loop {
+                        buf.clear();
+                        match lines.read_until(b'\n', &mut buf).await {
+                            Ok(0) => {
+                                // eof, reconnect
+                                break;
+                            }
+                            Ok(_) => {
+                                let event: DockerEvent = match serde_json::from_slice(&buf) {
+                                    Ok(e) => e,
+                                    Err(e) => {
+                                        warn!("Failed to parse docker event: {e}");
+                                        continue;
+                                    }
+                                };
+                                let image = event
+                                    .actor
+                                    .attributes
+                                    .image
+                                    .rsplit_once(":")
+                                    .map(|(image, _)| image.to_string())
+                                    .unwrap_or(event.actor.attributes.image);
+                                let msg = match event.status.as_str() {
+                                    "start" => ContainerEvent::Start { id: event.id, image },
+                                    "die" | "stop" => ContainerEvent::Stop { id: event.id },
+                                    _ => continue,
+                                };
+                                if tx.send(msg).await.is_err() {
+                                    // Dispatcher is gone, nothing left to do.
+                                    return Ok(());
+                                }
+                            }
+                            Err(e) => {
+                                return Err(format!("line read loop failed: {e}"));
+                            }
+                        }
+                    }
+                    Ok(())
+            } => {
+                if let Err(e) = r {
+                    warn!("Events stream had an issue: {}", e);
+                }
+            }
+        }
+
+        select! {
+            _ = cancel.cancelled() => {
+                break;
+            }
+            _ = sleep(backoff) => {}
+        }
+        backoff = (backoff * 2).min(std::time::Duration::from_secs(30));
+    }
+}
+
+async fn watcher(
+    source: Box<dyn LogSource>,
+    source_label: Vec<(String, String)>,
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    cancel: CancellationToken,
+) {
+    // The pattern is validated at config load time, so compilation can't fail here.
+    let re = Regex::new(&config.log_pattern).expect("log_pattern was validated at load time");
+    loop {
+        select! {
+            _ = cancel.cancelled() => {
+                break;
+            }
+            r = async {
+                    // Convert to async reader
+                    let stream_reader = tokio_util::io::StreamReader::new(source.stream().await?);
 
                     let mut lines = BufReader::new(stream_reader);
                     let mut buf = Vec::new();
@@ -221,25 +452,36 @@ async fn watcher(
                                 break;
                             },
                             Ok(n) => {
+                                metrics.line_read();
                                 let line = &buf[..n];
                                 re.captures(Input::new(line), &mut captures);
                                 if captures.is_match() {
-                                    let Some(status) = captures.get_group(1) else {
-                                        continue;
-                                    };
-                                    let status = str::from_utf8(&line[status.range()]).expect("invalid utf-8 for status capture, this should never happen.");
-                                    let Ok(status) = u16::from_str(status) else {
-                                        error!("Failed to parse status code as u16 from '{}'", status);
-                                        continue;
-                                    };
-                                    let Some(domain) = captures.get_group(2) else {
+                                    // Build the label set from whatever capture groups the
+                                    // config maps, skipping the line if any are missing or
+                                    // not valid utf-8.
+                                    let mut label: Vec<(String, String)> = Vec::with_capacity(config.captures.len());
+                                    let mut complete = true;
+                                    for (name, group) in &config.captures {
+                                        let Some(span) = captures.get_group(*group) else {
+                                            complete = false;
+                                            break;
+                                        };
+                                        let Ok(value) = str::from_utf8(&line[span.range()]) else {
+                                            // capture values should be valid utf-8, just ignore if they're not
+                                            complete = false;
+                                            break;
+                                        };
+                                        label.push((name.clone(), value.to_string()));
+                                    }
+                                    if !complete {
+                                        metrics.line_unmatched();
                                         continue;
-                                    };
-                                    let Ok(domain) = str::from_utf8(&line[domain.range()]) else {
-                                        // domains should be valid utf-8, just ignore if they're not
-                                        continue;
-                                    };
-                                    metrics.request(domain.to_string(), status).await;
+                                    }
+                                    // Keep the label set in a stable order so equal sets coincide.
+                                    label.sort();
+                                    metrics.request(label).await;
+                                } else {
+                                    metrics.line_unmatched();
                                 }
                             },
                             Err(e) => {
@@ -250,7 +492,8 @@ async fn watcher(
                     Ok(())
             } => {
                 if let Err(e) = r {
-                    warn!("Watcher for {container_id} had an issue: {}", e);
+                    warn!("Watcher had an issue: {}", e);
+                    metrics.reconnect(source_label.clone()).await;
                 }
             }
         }
@@ -274,9 +517,46 @@ struct DockerContainer {
     image: String,
 }
 
-async fn list_containers(client: Client) -> Result<Vec<DockerContainer>, String> {
-    let res = client
-        .get("http://docker/v1.30/containers/json")
+#[derive(Deserialize)]
+struct DockerEvent {
+    status: String,
+    id: String,
+    #[serde(rename = "Actor")]
+    actor: DockerEventActor,
+}
+
+#[derive(Deserialize)]
+struct DockerEventActor {
+    #[serde(rename = "Attributes")]
+    attributes: DockerEventAttributes,
+}
+
+#[derive(Deserialize)]
+struct DockerEventAttributes {
+    image: String,
+}
+
+/// Build the daemon-side `label` filter values from `docker_labels`: `key` for
+/// a presence match, `key=value` for an exact-value match.
+fn label_filters(config: &Config) -> Vec<String> {
+    config
+        .docker_labels
+        .iter()
+        .map(|(key, value)| match value {
+            Some(value) => format!("{key}={value}"),
+            None => key.clone(),
+        })
+        .collect()
+}
+
+async fn list_containers(config: &Config, client: Client) -> Result<Vec<DockerContainer>, String> {
+    let mut req = client.get("http://docker/v1.30/containers/json");
+    let labels = label_filters(config);
+    if !labels.is_empty() {
+        let filters = serde_json::json!({ "label": labels }).to_string();
+        req = req.query(&[("filters", filters)]);
+    }
+    let res = req
         .send()
         .await
         .map_err(|e| format!("request failed: {e:?}"))?;
@@ -297,3 +577,33 @@ async fn list_containers(client: Client) -> Result<Vec<DockerContainer>, String>
         .await
         .map_err(|e| format!("could not parse response body: {e}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_filters_render_presence_and_value() {
+        let mut config = Config::default();
+        config.docker_labels = std::collections::HashMap::from([
+            ("role".to_string(), Some("grab".to_string())),
+            ("managed".to_string(), None),
+        ]);
+        let mut filters = label_filters(&config);
+        filters.sort();
+        assert_eq!(filters, vec!["managed".to_string(), "role=grab".to_string()]);
+    }
+
+    #[test]
+    fn label_filters_empty_without_labels() {
+        let config = Config::default();
+        assert!(label_filters(&config).is_empty());
+    }
+
+    #[test]
+    fn image_matches_unconstrained_without_images() {
+        let mut config = Config::default();
+        config.docker_images.clear();
+        assert!(image_matches(&config, "anything"));
+    }
+}
@@ -6,34 +6,42 @@ use axum::routing::get;
 use axum::Router;
 use log::error;
 use prometheus_client::encoding::text::encode;
-use prometheus_client::encoding::EncodeLabelSet;
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::Registry;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::select;
 use tokio::sync::RwLock;
 use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 
-/// How often to run cleanup
-const CLEANUP_INTERVAL_SECS: u64 = 10;
-/// After how long to remove an entry
-const CLEANUP_ENTRY_TTL_SECS: u64 = 60;
-
-#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
-struct RequestLabel {
-    domain: String,
-    status_code: u16,
-}
+/// A request's label set, as `(name, value)` pairs. The names are whatever the
+/// configured `captures` mapping declares, so the label schema isn't fixed to a
+/// particular log format. Kept sorted by the caller so equal label sets hash
+/// and compare equal regardless of capture iteration order.
+type RequestLabel = Vec<(String, String)>;
 
 pub struct Metrics {
     cancel: CancellationToken,
     pub registry: Registry,
+    cleanup_interval: Duration,
+    entry_ttl: Duration,
     domain_requests: Family<RequestLabel, Counter>,
     domain_last_seen: RwLock<HashMap<RequestLabel, Instant>>,
+    /// Number of watchers the dispatcher is currently running.
+    watched_containers: Gauge,
+    /// Every log line the watchers read.
+    log_lines: Counter,
+    /// Lines that didn't match the pattern or were missing a mapped capture,
+    /// so operators can spot log-format drift.
+    log_lines_unmatched: Counter,
+    /// Per-source count of log-stream errors that triggered a reconnect: keyed
+    /// by container id for the Docker backend, by path/address for file/tcp.
+    reconnects: Family<RequestLabel, Counter>,
+    reconnects_last_seen: RwLock<HashMap<RequestLabel, Instant>>,
 }
 
 impl Metrics {
@@ -46,11 +54,42 @@ impl Metrics {
             "A counter of requests per domain.",
             domain_requests.clone(),
         );
+        let watched_containers = Gauge::default();
+        registry.register(
+            "watched_containers",
+            "Number of containers currently being watched.",
+            watched_containers.clone(),
+        );
+        let log_lines = Counter::default();
+        registry.register(
+            "log_lines",
+            "Total log lines read from all sources.",
+            log_lines.clone(),
+        );
+        let log_lines_unmatched = Counter::default();
+        registry.register(
+            "log_lines_unmatched",
+            "Log lines that did not match the pattern or were missing a mapped capture.",
+            log_lines_unmatched.clone(),
+        );
+        let reconnects = Family::<RequestLabel, Counter>::default();
+        registry.register(
+            "watcher_reconnects",
+            "A counter of log-stream errors that triggered a reconnect, per log source.",
+            reconnects.clone(),
+        );
         let metrics = Arc::new(Self {
             cancel,
             registry,
+            cleanup_interval: config.cleanup_interval,
+            entry_ttl: config.entry_ttl,
             domain_requests,
             domain_last_seen: RwLock::new(HashMap::new()),
+            watched_containers,
+            log_lines,
+            log_lines_unmatched,
+            reconnects,
+            reconnects_last_seen: RwLock::new(HashMap::new()),
         });
         tokio::spawn(metrics.clone().serve(config.listen_address.clone()));
         tokio::spawn(metrics.clone().periodic_cleanup());
@@ -78,11 +117,11 @@ impl Metrics {
     pub async fn periodic_cleanup(self: Arc<Self>) {
         loop {
             select! {
-                _ = self.cancel.cancelled() => {},
-                _ = sleep(std::time::Duration::from_secs(CLEANUP_INTERVAL_SECS)) => {}
+                _ = self.cancel.cancelled() => break,
+                _ = sleep(self.cleanup_interval) => {}
             }
             let now = Instant::now();
-            let ttl = std::time::Duration::from_secs(CLEANUP_ENTRY_TTL_SECS);
+            let ttl = self.entry_ttl;
             let mut last_seen = self.domain_last_seen.write().await;
             last_seen.retain(|label, last_seen| {
                 if now.duration_since(*last_seen) > ttl {
@@ -92,14 +131,49 @@ impl Metrics {
                     true
                 }
             });
+            drop(last_seen);
+            let mut reconnects_last_seen = self.reconnects_last_seen.write().await;
+            reconnects_last_seen.retain(|label, last_seen| {
+                if now.duration_since(*last_seen) > ttl {
+                    self.reconnects.remove(&label);
+                    false
+                } else {
+                    true
+                }
+            });
         }
     }
 
-    pub async fn request(self: &Arc<Self>, domain: String, status_code: u16) {
-        let label = RequestLabel {
-            domain,
-            status_code,
-        };
+    /// Record that the dispatcher started watching a container.
+    pub fn watcher_started(&self) {
+        self.watched_containers.inc();
+    }
+
+    /// Record that the dispatcher stopped watching a container.
+    pub fn watcher_stopped(&self) {
+        self.watched_containers.dec();
+    }
+
+    /// Count a log line read from a source.
+    pub fn line_read(&self) {
+        self.log_lines.inc();
+    }
+
+    /// Count a log line that didn't match the pattern or was missing a capture.
+    pub fn line_unmatched(&self) {
+        self.log_lines_unmatched.inc();
+    }
+
+    /// Record a log-stream error/reconnect for the given source label.
+    pub async fn reconnect(self: &Arc<Self>, label: RequestLabel) {
+        self.reconnects.get_or_create(&label).inc();
+        self.reconnects_last_seen
+            .write()
+            .await
+            .insert(label, Instant::now());
+    }
+
+    pub async fn request(self: &Arc<Self>, label: RequestLabel) {
         self.domain_requests.get_or_create(&label).inc();
         let mut last_seen = self.domain_last_seen.write().await;
         if let Some(last_seen_time) = last_seen.get_mut(&label) {